@@ -1,7 +1,9 @@
 use std::{
     borrow::Cow,
+    collections::HashMap,
     fmt::Display,
     io::{self, Write},
+    sync::Arc,
 };
 
 use indexmap::IndexMap;
@@ -12,18 +14,72 @@ use crate::{
     value::{ElementValue, NodeValue},
 };
 
+mod pp;
+
+use pp::{Breaks, Printer};
+
 pub(crate) trait Print<Config, Context = ()> {
-    fn print(&self, f: &mut dyn Write, config: &Config, context: &Context) -> std::io::Result<()>;
+    fn print(&self, p: &mut Printer, config: &Config, context: &Context);
+}
+
+/// The node kinds an annotator can be asked to decorate. Every variant
+/// except `Declaration` carries the node's [`DocKey`] so an annotator can
+/// look anything else it needs up in the document itself.
+#[derive(Debug, Clone, Copy)]
+pub enum AnnNode {
+    Element(DocKey),
+    Text(DocKey),
+    CData(DocKey),
+    Comment(DocKey),
+    Declaration,
+}
+
+/// An extension point for decorating serialized output around individual
+/// nodes, e.g. ANSI color codes for terminal highlighting, `<span>` wrappers
+/// for an HTML pretty-printer, or comments injected around specific keys —
+/// the same hook rustc's own pretty printer exposes to its annotators.
+pub trait PpAnn {
+    fn pre(&self, node: AnnNode, out: &mut dyn Write) -> io::Result<()> {
+        let _ = (node, out);
+        Ok(())
+    }
+
+    fn post(&self, node: AnnNode, out: &mut dyn Write) {
+        let _ = (node, out);
+    }
 }
 
+/// The default annotator: writes nothing extra.
+pub struct NoAnn;
+
+impl PpAnn for NoAnn {}
+
 #[derive(Debug, Clone, Default)]
 pub struct Config {
     pub is_pretty: bool,
     pub indent: usize,
     pub max_line_length: usize,
     pub entity_mode: EntityMode,
+    pub canonical: Option<Canonical>,
 }
 
+/// Canonical XML (C14N) output: byte-stable serialization suitable for
+/// hashing and digital signatures. Attribute order, quoting and
+/// empty-element syntax in the source do not affect the output; comments,
+/// CDATA sections and the document type declaration are written as they
+/// are, and line endings are not normalized (a `\r` in text or an
+/// attribute value is written as `&#xD;`).
+///
+/// Deliberately smaller than the spec's `Canonical { exclusive: bool }`:
+/// there is no namespace-URI resolution in this crate, so only the
+/// inclusive form of C14N is supported. This is a scoped-down surface, not
+/// an oversight — an `exclusive` toggle would need ancestor-scope
+/// resolution to know which inherited namespace declarations to drop, and
+/// nothing here tracks that. Add the field back if/when that resolution
+/// exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canonical;
+
 impl Config {
     pub fn default_pretty() -> Self {
         Config {
@@ -31,13 +87,42 @@ impl Config {
             indent: 2,
             max_line_length: 120,
             entity_mode: EntityMode::Standard,
+            canonical: None,
+        }
+    }
+
+    /// Canonical XML output per [`Canonical`]: no indentation, sorted and
+    /// double-quoted attributes, explicit `<foo></foo>` pairs instead of
+    /// `<foo />`, and no XML declaration.
+    pub fn canonical() -> Self {
+        Config {
+            is_pretty: false,
+            indent: 0,
+            max_line_length: 0,
+            entity_mode: EntityMode::Standard,
+            canonical: Some(Canonical),
+        }
+    }
+
+    /// Whether indentation and wrapping are active. Canonical mode always
+    /// overrides this to `false`, regardless of `is_pretty`.
+    fn is_pretty(&self) -> bool {
+        self.is_pretty && self.canonical.is_none()
+    }
+
+    /// The column budget handed to the layout engine: the configured
+    /// wrap column when pretty-printing, or "never wrap" otherwise.
+    fn line_length(&self) -> isize {
+        if self.is_pretty() {
+            self.max_line_length as isize
+        } else {
+            isize::MAX
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub(crate) struct State<'a> {
-    pub indent: usize,
     pub key: DocKey,
     pub doc: &'a Document,
 }
@@ -45,27 +130,13 @@ pub(crate) struct State<'a> {
 impl<'a> State<'a> {
     pub(crate) fn new(document: &'a Document) -> Self {
         Self {
-            indent: 0,
             doc: document,
             key: document.root_key.0,
         }
     }
 
-    fn with_indent(&self, config: &Config) -> Self {
-        if !config.is_pretty {
-            return self.clone();
-        }
-
-        State {
-            indent: self.indent + config.indent,
-            key: self.key,
-            doc: self.doc,
-        }
-    }
-
     fn with_key(&self, key: DocKey) -> Self {
         State {
-            indent: self.indent,
             key,
             doc: self.doc,
         }
@@ -73,33 +144,24 @@ impl<'a> State<'a> {
 }
 
 impl Print<Config, State<'_>> for Declaration {
-    fn print(
-        &self,
-        f: &mut dyn Write,
-        config: &Config,
-        _context: &State<'_>,
-    ) -> std::io::Result<()> {
-        write!(f, "<?xml ")?;
+    fn print(&self, p: &mut Printer, _config: &Config, _context: &State<'_>) {
+        p.ann_pre(AnnNode::Declaration);
+        p.string("<?xml ");
 
         if let Some(version) = self.version.as_deref() {
-            write!(f, "version=\"{}\" ", version)?;
+            p.string(format!("version=\"{}\" ", version));
         }
 
         if let Some(encoding) = self.encoding.as_deref() {
-            write!(f, "encoding=\"{}\" ", encoding)?;
+            p.string(format!("encoding=\"{}\" ", encoding));
         }
 
         if let Some(standalone) = self.standalone.as_deref() {
-            write!(f, "standalone=\"{}\" ", standalone)?;
+            p.string(format!("standalone=\"{}\" ", standalone));
         }
 
-        write!(f, "?>")?;
-
-        if config.is_pretty {
-            writeln!(f)?;
-        }
-
-        Ok(())
+        p.string("?>");
+        p.ann_post(AnnNode::Declaration);
     }
 }
 
@@ -121,25 +183,32 @@ impl Display for Document {
             config.max_line_length = precision;
         }
 
-        self.print(&mut FmtWriter(f), &config, &State::new(self))
+        let mut printer = Printer::new();
+        self.print(&mut printer, &config, &State::new(self));
+        printer
+            .print(&mut FmtWriter(f), config.line_length(), &NoAnn)
             .map_err(|_| std::fmt::Error)
     }
 }
 
 impl Print<Config, State<'_>> for Document {
-    fn print(
-        &self,
-        f: &mut dyn Write,
-        config: &Config,
-        context: &State<'_>,
-    ) -> std::io::Result<()> {
-        if let Some(decl) = self.decl.as_ref() {
-            Print::print(decl, f, &config, &context)?;
+    fn print(&self, p: &mut Printer, config: &Config, context: &State<'_>) {
+        // Canonical XML never carries an XML declaration.
+        if config.canonical.is_none() {
+            if let Some(decl) = self.decl.as_ref() {
+                Print::print(decl, p, config, context);
+                if config.is_pretty() {
+                    p.hardbreak();
+                }
+            }
         }
 
         for node in self.before.iter() {
             let node_value = self.nodes.get(node.as_key()).unwrap();
-            node_value.print(f, config, &context.with_key(node.as_key()))?;
+            node_value.print(p, config, &context.with_key(node.as_key()));
+            if config.is_pretty() {
+                p.hardbreak();
+            }
         }
 
         let element = self
@@ -149,166 +218,231 @@ impl Print<Config, State<'_>> for Document {
             .as_element()
             .unwrap();
 
-        element.print(f, config, &context.with_key(self.root_key.0))?;
+        element.print(p, config, &context.with_key(self.root_key.0));
+        if config.is_pretty() {
+            p.hardbreak();
+        }
 
         for node in self.after.iter() {
             let node_value = self.nodes.get(node.as_key()).unwrap();
-            node_value.print(f, config, &context.with_key(node.as_key()))?;
+            node_value.print(p, config, &context.with_key(node.as_key()));
+            if config.is_pretty() {
+                p.hardbreak();
+            }
         }
+    }
+}
 
-        Ok(())
+impl Document {
+    /// Serializes the document, invoking `ann`'s hooks around each node.
+    /// See [`PpAnn`] for the available extension points. Panics if `ann`'s
+    /// `pre` hook returns an error.
+    pub fn to_string_with_ann(&self, config: &Config, ann: &dyn PpAnn) -> String {
+        let mut printer = Printer::new();
+        self.print(&mut printer, config, &State::new(self));
+
+        let mut buf = Vec::new();
+        printer
+            .print(&mut buf, config.line_length(), ann)
+            .expect("annotator's pre hook failed (writing to a Vec<u8> cannot fail)");
+        String::from_utf8(buf).expect("serialized XML is always valid UTF-8")
     }
 }
 
-fn fmt_attrs(
-    f: &mut dyn Write,
-    tag: &str,
-    config: &Config,
-    context: &State,
-    attrs: &IndexMap<String, String>,
-) -> io::Result<()> {
-    let line_length = tag.len()
-        + 2
-        + attrs
-            .iter()
-            .fold(0usize, |acc, (k, v)| acc + k.len() + v.len() + 4);
-
-    let is_newlines = config.is_pretty && line_length > config.max_line_length;
-    let context = context.with_indent(config);
-
-    let mut iter = attrs.iter();
-
-    if let Some((k, v)) = iter.next() {
-        if is_newlines {
-            writeln!(f)?;
-            write!(f, "{:>indent$}", "", indent = context.indent)?;
+/// Orders attributes for canonical output: namespace declarations first
+/// (the default namespace, then prefixed declarations by prefix),
+/// followed by the remaining attributes ordered by namespace URI then
+/// local name. Lacking namespace-URI resolution, attributes are ordered by
+/// their own prefix in place of its URI, which gives C14N order only where
+/// the prefixes sort in the same order as the URIs they are bound to.
+fn canonical_attr_order(attrs: &IndexMap<String, String>) -> Vec<(&String, &String)> {
+    fn sort_key(key: &str) -> (bool, &str, &str) {
+        if key == "xmlns" {
+            (true, "", "")
+        } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+            (true, prefix, "")
+        } else if let Some((prefix, local)) = key.split_once(':') {
+            (false, prefix, local)
+        } else {
+            (false, "", key)
         }
-        write!(f, "{}=\"{}\"", k, process_entities(v, config.entity_mode))?;
-    } else {
-        return Ok(());
     }
 
-    for (k, v) in iter {
-        if is_newlines {
-            writeln!(f)?;
-            write!(f, "{:>indent$}", "", indent = context.indent)?;
+    let mut sorted: Vec<_> = attrs.iter().collect();
+    sorted.sort_by_key(|(k, _)| {
+        let (is_ns, prefix, local) = sort_key(k);
+        (!is_ns, prefix, local)
+    });
+    sorted
+}
+
+/// Lays out an element's attributes as an inconsistent group so that as
+/// many as possible pack onto the current line, wrapping only the ones
+/// that don't fit. In canonical mode, attributes are reordered per
+/// [`canonical_attr_order`] and escaped to the C14N minimum instead.
+fn print_attrs(p: &mut Printer, config: &Config, attrs: &IndexMap<String, String>) {
+    if attrs.is_empty() {
+        return;
+    }
+
+    let ordered: Vec<(&String, &String)> = if config.canonical.is_some() {
+        canonical_attr_order(attrs)
+    } else {
+        attrs.iter().collect()
+    };
+
+    p.begin(config.indent as isize, Breaks::Inconsistent);
+    for (k, v) in ordered {
+        p.brk(1, 0);
+        let value = if config.canonical.is_some() {
+            Cow::Owned(canonical_escape(v, true))
         } else {
-            write!(f, " ")?;
+            process_entities(v, &config.entity_mode, EntityContext::Attr)
+        };
+        p.string(format!("{}=\"{}\"", k, value));
+    }
+    p.end();
+}
+
+/// Escapes per C14N's exact minimum (<https://www.w3.org/TR/xml-c14n11/#Charset>):
+/// `&`, `<`, and `>` in text content; `&`, `<`, the active quote, and the
+/// whitespace bytes an XML parser would otherwise normalize away in
+/// attribute values. `\r` is escaped as `&#xD;` in both contexts; line
+/// endings are not otherwise normalized, so a `\r\n` in the input comes
+/// out as `&#xD;` followed by a literal newline. This is
+/// deliberately narrower than `process_entities`, which favors
+/// readability over the canonical form's exact minimum.
+fn canonical_escape(input: &str, in_attr: bool) -> String {
+    let mut s = String::with_capacity(input.len());
+    for ch in input.chars() {
+        match ch {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' if !in_attr => s.push_str("&gt;"),
+            '"' if in_attr => s.push_str("&quot;"),
+            '\t' if in_attr => s.push_str("&#x9;"),
+            '\n' if in_attr => s.push_str("&#xA;"),
+            '\r' => s.push_str("&#xD;"),
+            other => s.push(other),
         }
-        write!(f, "{}=\"{}\"", k, process_entities(v, config.entity_mode))?;
     }
+    s
+}
 
-    Ok(())
+/// Breaks `text` into words and lays them out as an inconsistent group so
+/// that mixed text content reflows instead of being dumped as one run.
+/// Only used in pretty mode, since collapsing whitespace runs between
+/// words is a wrapping affordance, not something compact output should
+/// do to otherwise-significant text.
+fn print_text(p: &mut Printer, text: &str) {
+    let mut words = text.split_whitespace();
+
+    let Some(first) = words.next() else {
+        return;
+    };
+
+    p.begin(0, Breaks::Inconsistent);
+    p.string(first.to_owned());
+    for word in words {
+        p.brk(1, 0);
+        p.string(word.to_owned());
+    }
+    p.end();
 }
 
 impl Print<Config, State<'_>> for ElementValue {
-    fn print(
-        &self,
-        f: &mut dyn Write,
-        config: &Config,
-        context: &State<'_>,
-    ) -> std::io::Result<()> {
-        if self.children.is_empty() {
-            match context.doc.attrs.get(context.key) {
-                Some(attrs) if !attrs.is_empty() => {
-                    write!(f, "{:>indent$}<{} ", "", self.name, indent = context.indent)?;
-                    fmt_attrs(f, &self.name, config, context, attrs)?;
-                    write!(f, " />")?;
-                    if config.is_pretty {
-                        writeln!(f)?;
-                    }
-                    return Ok(());
-                }
-                _ => {
-                    write!(
-                        f,
-                        "{:>indent$}<{} />",
-                        "",
-                        self.name,
-                        indent = context.indent
-                    )?;
-                    if config.is_pretty {
-                        writeln!(f)?;
-                    }
-                    return Ok(());
-                }
-            }
+    fn print(&self, p: &mut Printer, config: &Config, context: &State<'_>) {
+        let node = AnnNode::Element(context.key);
+        p.ann_pre(node);
+
+        let attrs = context.doc.attrs.get(context.key);
+
+        p.string(format!("<{}", self.name));
+
+        if let Some(attrs) = attrs {
+            print_attrs(p, config, attrs);
         }
 
-        match context.doc.attrs.get(context.key) {
-            Some(attrs) if !attrs.is_empty() => {
-                write!(f, "{:>indent$}<{} ", "", self.name, indent = context.indent)?;
-                fmt_attrs(f, &self.name, config, context, attrs)?;
-                write!(f, ">")?;
-                if config.is_pretty {
-                    writeln!(f)?;
-                }
-            }
-            _ => {
-                write!(f, "{:>indent$}<{}>", "", self.name, indent = context.indent)?;
-                if config.is_pretty {
-                    writeln!(f)?;
-                }
+        if self.children.is_empty() {
+            // Canonical XML always expands empty elements into explicit
+            // start/end tag pairs rather than `<foo />`.
+            if config.canonical.is_some() {
+                p.string(format!("></{}>", self.name));
+            } else {
+                p.string(" />");
             }
+            p.ann_post(node);
+            return;
         }
 
-        let child_context = context.with_indent(config);
+        p.string(">");
 
+        p.begin(config.indent as isize, Breaks::Consistent);
         for child in self.children.iter() {
+            p.brk(0, 0);
             let value = context.doc.nodes.get(child.as_key()).unwrap();
-            value.print(f, config, &child_context.with_key(child.as_key()))?;
-        }
-        write!(
-            f,
-            "{:>indent$}</{}>",
-            "",
-            self.name,
-            indent = context.indent
-        )?;
-
-        if config.is_pretty {
-            writeln!(f)?;
+            value.print(p, config, &context.with_key(child.as_key()));
         }
+        p.brk(0, -(config.indent as isize));
+        p.string(format!("</{}>", self.name));
+        p.end();
 
-        Ok(())
+        p.ann_post(node);
     }
 }
 
 impl Print<Config, State<'_>> for NodeValue {
-    fn print(
-        &self,
-        f: &mut dyn Write,
-        config: &Config,
-        context: &State<'_>,
-    ) -> std::io::Result<()> {
-        if let NodeValue::Element(e) = self {
-            return e.print(f, config, context);
-        }
+    fn print(&self, p: &mut Printer, config: &Config, context: &State<'_>) {
+        let ann_node = match self {
+            NodeValue::Element(_) => None,
+            NodeValue::Text(_) => Some(AnnNode::Text(context.key)),
+            NodeValue::CData(_) => Some(AnnNode::CData(context.key)),
+            NodeValue::DocumentType(_) => None,
+            NodeValue::Comment(_) => Some(AnnNode::Comment(context.key)),
+        };
 
-        if config.is_pretty {
-            write!(f, "{:>indent$}", "", indent = context.indent)?;
+        if let Some(node) = ann_node {
+            p.ann_pre(node);
         }
 
         match self {
-            NodeValue::Text(t) => write!(f, "{}", &*process_entities(t, config.entity_mode).trim()),
-            NodeValue::CData(t) => write!(f, "<![CDATA[{}]]>", t),
-            NodeValue::DocumentType(t) => write!(f, "<!DOCTYPE{}>", t),
-            NodeValue::Comment(t) => write!(f, "<!--{}-->", t),
-            NodeValue::Element(_) => unreachable!(),
-        }?;
-
-        if config.is_pretty {
-            writeln!(f)?;
+            NodeValue::Element(e) => e.print(p, config, context),
+            NodeValue::Text(t) if config.canonical.is_some() => {
+                p.string(canonical_escape(t, false))
+            }
+            NodeValue::Text(t) => {
+                let escaped = process_entities(t, &config.entity_mode, EntityContext::Text);
+                if config.is_pretty() {
+                    // Reflowing is a pretty-printing affordance; in compact
+                    // mode, emit the (trimmed) text as-is so internal
+                    // whitespace round-trips rather than collapsing runs
+                    // of spaces to one, as word-wrapping would.
+                    print_text(p, &escaped);
+                } else {
+                    p.string(escaped.trim());
+                }
+            }
+            NodeValue::CData(t) => p.string(format!("<![CDATA[{}]]>", t)),
+            NodeValue::DocumentType(t) => p.string(format!("<!DOCTYPE{}>", t)),
+            NodeValue::Comment(t) => p.string(format!("<!--{}-->", t)),
         }
 
-        Ok(())
+        if let Some(node) = ann_node {
+            p.ann_post(node);
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EntityMode {
     Standard,
     Hex,
+    Decimal,
+    /// Looks up each code point needing escaping in `table` (the value is
+    /// the bare entity name, e.g. `"nbsp"`, without the surrounding `&`/`;`)
+    /// and falls back to [`EntityMode::Standard`]'s rules for any code
+    /// point the table doesn't cover.
+    Named { table: Arc<HashMap<char, String>> },
 }
 
 impl Default for EntityMode {
@@ -317,35 +451,77 @@ impl Default for EntityMode {
     }
 }
 
-fn process_entities(input: &str, mode: EntityMode) -> Cow<'_, str> {
-    if input.contains(['<', '>', '\'', '"', '&']) || input.contains(|c: char| c.is_ascii_control())
+/// Which syntactic position a string is being escaped into — attribute
+/// values and text content require escaping different characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntityContext {
+    /// A double-quoted attribute value.
+    Attr,
+    /// Content between a start and end tag.
+    Text,
+}
+
+/// Whether the character at `pos` in `input` needs escaping for `context`:
+/// in text, only `<`, `&`, and a `>` that closes a `]]>` sequence (the one
+/// construct that would otherwise be ambiguous with a CDATA terminator);
+/// in attribute values, `<`, `&`, and the active quote (always `"`, since
+/// attribute values are always double-quoted). ASCII control characters
+/// are always escaped, in either context, since a parser can't round-trip
+/// them literally. A [`EntityMode::Named`] table can additionally mark
+/// any other code point as needing escaping, e.g. `'\u{a0}'` for `&nbsp;`.
+fn needs_escape(input: &str, pos: usize, ch: char, context: EntityContext, mode: &EntityMode) -> bool {
+    match ch {
+        '&' | '<' => true,
+        '"' if context == EntityContext::Attr => true,
+        '>' if context == EntityContext::Text => input[..pos].ends_with("]]"),
+        ch if ch.is_ascii_control() => true,
+        ch => matches!(mode, EntityMode::Named { table } if table.contains_key(&ch)),
+    }
+}
+
+/// Renders a single escaped character per `mode`.
+fn escape_char(ch: char, mode: &EntityMode) -> String {
+    match mode {
+        EntityMode::Standard => standard_entity(ch),
+        EntityMode::Hex => format!("&#x{:>04X};", ch as u32),
+        EntityMode::Decimal => format!("&#{};", ch as u32),
+        EntityMode::Named { table } => table
+            .get(&ch)
+            .map(|name| format!("&{};", name))
+            .unwrap_or_else(|| standard_entity(ch)),
+    }
+}
+
+/// The standard named entity for a character needing escaping, or a hex
+/// reference for anything without one (i.e. ASCII control characters, or
+/// a [`EntityMode::Named`] code point not covered by its table).
+fn standard_entity(ch: char) -> String {
+    match ch {
+        '&' => "&amp;".to_owned(),
+        '<' => "&lt;".to_owned(),
+        '>' => "&gt;".to_owned(),
+        '"' => "&quot;".to_owned(),
+        _ => format!("&#x{:>04X};", ch as u32),
+    }
+}
+
+fn process_entities<'a>(input: &'a str, mode: &EntityMode, context: EntityContext) -> Cow<'a, str> {
+    if !input
+        .char_indices()
+        .any(|(pos, ch)| needs_escape(input, pos, ch, context, mode))
     {
-        let mut s = String::with_capacity(input.len());
-        input.chars().for_each(|ch| {
-            s.push_str(match (mode, ch) {
-                (EntityMode::Standard, '&') => "&amp;",
-                (EntityMode::Standard, '\'') => "&apos;",
-                (EntityMode::Standard, '"') => "&quot;",
-                (EntityMode::Standard, '<') => "&lt;",
-                (EntityMode::Standard, '>') => "&gt;",
-                (EntityMode::Hex, '&' | '\'' | '"' | '<' | '>') => {
-                    s.push_str(&format!("&#x{:>04X};", ch as u32));
-                    return;
-                }
-                (_, ch) if ch.is_ascii_control() => {
-                    s.push_str(&format!("&#x{:>04X};", ch as u32));
-                    return;
-                }
-                (_, other) => {
-                    s.push(other);
-                    return;
-                }
-            })
-        });
-        Cow::Owned(s)
-    } else {
-        Cow::Borrowed(input)
+        return Cow::Borrowed(input);
+    }
+
+    let mut s = String::with_capacity(input.len());
+    for (pos, ch) in input.char_indices() {
+        if needs_escape(input, pos, ch, context, mode) {
+            s.push_str(&escape_char(ch, mode));
+        } else {
+            s.push(ch);
+        }
     }
+    Cow::Owned(s)
 }
 
 struct FmtWriter<'a, 'b>(&'b mut std::fmt::Formatter<'a>);
@@ -363,3 +539,165 @@ impl Write for FmtWriter<'_, '_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_long_text_at_max_line_length() {
+        let mut p = Printer::new();
+        print_text(&mut p, "one two three four five six seven eight nine ten");
+
+        let mut buf = Vec::new();
+        p.print(&mut buf, 20, &NoAnn).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+
+        for line in out.lines() {
+            assert!(line.chars().count() <= 20, "line exceeded budget: {line:?}");
+        }
+        assert!(out.contains('\n'), "expected the text to wrap: {out:?}");
+    }
+
+    #[test]
+    fn packs_attributes_that_fit_and_wraps_the_rest() {
+        let mut attrs = IndexMap::new();
+        attrs.insert("a".to_string(), "1".to_string());
+        attrs.insert("b".to_string(), "2".to_string());
+        attrs.insert("ccccccccccccccccccccccc".to_string(), "3".to_string());
+
+        let config = Config {
+            indent: 2,
+            ..Config::default()
+        };
+        let mut p = Printer::new();
+        print_attrs(&mut p, &config, &attrs);
+
+        let mut buf = Vec::new();
+        p.print(&mut buf, 12, &NoAnn).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+
+        assert!(
+            out.contains("a=\"1\" b=\"2\""),
+            "short attributes should pack onto one line: {out:?}"
+        );
+        assert!(
+            out.contains("\n  ccccccccccccccccccccccc=\"3\""),
+            "the oversized attribute should wrap onto its own indented line: {out:?}"
+        );
+    }
+
+    #[test]
+    fn orders_namespace_declarations_before_other_attributes() {
+        let mut attrs = IndexMap::new();
+        attrs.insert("b:attr".to_string(), "1".to_string());
+        attrs.insert("xmlns:b".to_string(), "urn:b".to_string());
+        attrs.insert("attr".to_string(), "2".to_string());
+        attrs.insert("xmlns".to_string(), "urn:default".to_string());
+
+        let ordered: Vec<&str> = canonical_attr_order(&attrs)
+            .into_iter()
+            .map(|(k, _)| k.as_str())
+            .collect();
+
+        assert_eq!(ordered, vec!["xmlns", "xmlns:b", "attr", "b:attr"]);
+    }
+
+    #[test]
+    fn canonical_escape_differs_between_text_and_attr() {
+        assert_eq!(
+            canonical_escape("a < b > c & d", false),
+            "a &lt; b &gt; c &amp; d"
+        );
+        assert_eq!(
+            canonical_escape("a < b > c & d \"q\"", true),
+            "a &lt; b > c &amp; d &quot;q&quot;"
+        );
+    }
+
+    #[test]
+    fn canonical_escape_escapes_tab_and_newline_only_in_attrs() {
+        assert_eq!(canonical_escape("a\tb\nc\rd", false), "a\tb\nc&#xD;d");
+        assert_eq!(canonical_escape("a\tb\nc\rd", true), "a&#x9;b&#xA;c&#xD;d");
+    }
+
+    #[test]
+    fn text_escapes_gt_only_in_cdata_end_sequence() {
+        let plain = process_entities("a > b", &EntityMode::Standard, EntityContext::Text);
+        assert_eq!(plain, "a > b");
+
+        let cdata_end = process_entities("a]]> b", &EntityMode::Standard, EntityContext::Text);
+        assert_eq!(cdata_end, "a]]&gt; b");
+    }
+
+    #[test]
+    fn attr_never_escapes_gt_but_always_escapes_the_quote() {
+        let out = process_entities("a > b \"q\"", &EntityMode::Standard, EntityContext::Attr);
+        assert_eq!(out, "a > b &quot;q&quot;");
+    }
+
+    #[test]
+    fn named_mode_falls_back_to_standard_for_uncovered_code_points() {
+        let mut table = HashMap::new();
+        table.insert('<', "foo".to_string());
+        let mode = EntityMode::Named {
+            table: Arc::new(table),
+        };
+
+        let out = process_entities("<>&", &mode, EntityContext::Text);
+        assert_eq!(out, "&foo;>&amp;");
+    }
+
+    #[test]
+    fn named_mode_escapes_non_structural_code_points_in_its_table() {
+        let mut table = HashMap::new();
+        table.insert('\u{a0}', "nbsp".to_string());
+        let mode = EntityMode::Named {
+            table: Arc::new(table),
+        };
+
+        let out = process_entities("a\u{a0}b", &mode, EntityContext::Text);
+        assert_eq!(
+            out, "a&nbsp;b",
+            "a code point present in the table should be escaped even though it's not structural"
+        );
+    }
+
+    #[test]
+    fn hex_mode_zero_pads_to_four_digits() {
+        let out = process_entities("\n", &EntityMode::Hex, EntityContext::Text);
+        assert_eq!(out, "&#x000A;");
+    }
+
+    #[test]
+    fn print_text_collapses_internal_whitespace_runs() {
+        let mut p = Printer::new();
+        print_text(&mut p, "a    b");
+
+        let mut buf = Vec::new();
+        p.print(&mut buf, 80, &NoAnn).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+
+        assert_eq!(
+            out, "a b",
+            "print_text reflows words, collapsing whitespace runs as a wrapping affordance"
+        );
+    }
+
+    #[test]
+    fn compact_mode_text_preserves_internal_whitespace() {
+        // Mirrors the non-pretty arm of `NodeValue::Text`: entities are
+        // escaped but the text is emitted verbatim (only trimmed) rather
+        // than reflowed through `print_text`, so multi-space runs round-trip
+        // instead of collapsing to a single space.
+        let escaped = process_entities("  a    b  ", &EntityMode::default(), EntityContext::Text);
+        let mut p = Printer::new();
+        p.string(escaped.trim());
+
+        let mut buf = Vec::new();
+        p.print(&mut buf, 80, &NoAnn).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+
+        assert_eq!(out, "a    b");
+    }
+}
@@ -0,0 +1,255 @@
+//! A small Oppen-style pretty-printing engine, in the tradition of Wadler's
+//! "prettier printer" and the algorithm rustc's own pretty printer is built
+//! on. Callers build up a [`Token`] stream describing *what* can break and
+//! where, and [`Printer::print`] decides *whether* it breaks by running the
+//! classic two-pass layout: a scan pass that measures how wide each group
+//! and break is, followed by a print pass that lays tokens out against the
+//! remaining line width.
+
+use std::io::{self, Write};
+
+use super::{AnnNode, PpAnn};
+
+/// Whether the breaks inside a [`Breaks::Consistent`] group all fire
+/// together once the group doesn't fit, or a [`Breaks::Inconsistent`] group
+/// only breaks the ones it needs to, packing as much as possible onto each
+/// line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Breaks {
+    Consistent,
+    Inconsistent,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AnnPhase {
+    Pre,
+    Post,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    String(String),
+    Break { blank: usize, offset: isize },
+    Begin { offset: isize, breaks: Breaks },
+    End,
+    Ann { phase: AnnPhase, node: AnnNode },
+    /// An unconditional line break, independent of any enclosing group's
+    /// fit — unlike [`Token::Break`], it always fires and resets the
+    /// column budget, for separators between top-level document nodes
+    /// rather than reflow within one.
+    Newline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Fits,
+    Break,
+}
+
+struct Frame {
+    mode: Mode,
+    breaks: Breaks,
+    indent: isize,
+}
+
+/// A token stream that can be laid out by the Oppen algorithm once the
+/// whole document has been lowered into it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Printer {
+    tokens: Vec<Token>,
+}
+
+impl Printer {
+    pub(crate) fn new() -> Self {
+        Self { tokens: Vec::new() }
+    }
+
+    /// Pushes a run of text that is never itself a break point.
+    pub(crate) fn string(&mut self, s: impl Into<String>) {
+        self.tokens.push(Token::String(s.into()));
+    }
+
+    /// Opens a group. `offset` is the additional indent applied to breaks
+    /// inside the group if it doesn't fit on the current line.
+    pub(crate) fn begin(&mut self, offset: isize, breaks: Breaks) {
+        self.tokens.push(Token::Begin { offset, breaks });
+    }
+
+    /// Closes the group most recently opened with [`Printer::begin`].
+    pub(crate) fn end(&mut self) {
+        self.tokens.push(Token::End);
+    }
+
+    /// A potential line break: `blank` spaces when the enclosing group
+    /// fits (or doesn't need this particular break), a newline plus indent
+    /// otherwise.
+    pub(crate) fn brk(&mut self, blank: usize, offset: isize) {
+        self.tokens.push(Token::Break { blank, offset });
+    }
+
+    /// Marks where an annotator's `pre` callback should fire once this
+    /// stream reaches the print pass.
+    pub(crate) fn ann_pre(&mut self, node: AnnNode) {
+        self.tokens.push(Token::Ann {
+            phase: AnnPhase::Pre,
+            node,
+        });
+    }
+
+    /// Marks where an annotator's `post` callback should fire.
+    pub(crate) fn ann_post(&mut self, node: AnnNode) {
+        self.tokens.push(Token::Ann {
+            phase: AnnPhase::Post,
+            node,
+        });
+    }
+
+    /// An unconditional line break that always fires, regardless of
+    /// whether it sits inside a group that fits. Used to separate
+    /// top-level document nodes, where a plain [`Token::Break`] would
+    /// either be swallowed (no enclosing group) or only fire when that
+    /// group overflows — neither of which is "always start a new line".
+    pub(crate) fn hardbreak(&mut self) {
+        self.tokens.push(Token::Newline);
+    }
+
+    fn width(token: &Token) -> isize {
+        match token {
+            Token::String(s) => s.chars().count() as isize,
+            Token::Break { blank, .. } => *blank as isize,
+            Token::Begin { .. } | Token::End | Token::Ann { .. } | Token::Newline => 0,
+        }
+    }
+
+    /// First pass (scan): walks the token stream and resolves the size of
+    /// every `Begin`/`Break` token. A `Begin`'s size is the total width
+    /// from it to its matching `End`; a `Break`'s size is the width until
+    /// the next break or the end of its group. Unmatched tokens (an
+    /// unclosed group at the end of the stream) are sized out to the end
+    /// of the input rather than treated as an error, since a partially
+    /// built stream should still lay out sensibly.
+    fn scan(&self) -> Vec<isize> {
+        let mut sizes = vec![0isize; self.tokens.len()];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut right_total: isize = 0;
+
+        fn close_break(tokens: &[Token], sizes: &mut [isize], stack: &mut Vec<usize>, right_total: isize) {
+            if let Some(&top) = stack.last() {
+                if matches!(tokens[top], Token::Break { .. }) {
+                    sizes[top] = right_total - sizes[top];
+                    stack.pop();
+                }
+            }
+        }
+
+        for (i, token) in self.tokens.iter().enumerate() {
+            match token {
+                Token::Begin { .. } => {
+                    stack.push(i);
+                    sizes[i] = right_total;
+                }
+                Token::Break { .. } => {
+                    close_break(&self.tokens, &mut sizes, &mut stack, right_total);
+                    stack.push(i);
+                    sizes[i] = right_total;
+                    right_total += Self::width(token);
+                }
+                Token::End => {
+                    close_break(&self.tokens, &mut sizes, &mut stack, right_total);
+                    if let Some(open) = stack.pop() {
+                        sizes[open] = right_total - sizes[open];
+                    }
+                }
+                Token::String(_) | Token::Ann { .. } | Token::Newline => {
+                    right_total += Self::width(token);
+                }
+            }
+        }
+
+        while let Some(open) = stack.pop() {
+            sizes[open] = right_total - sizes[open];
+        }
+
+        sizes
+    }
+
+    /// Second pass (print): replays the stream, picking `Fits` or `Break`
+    /// mode for each group against the sizes computed above and emitting
+    /// either inline whitespace or a newline plus indent for each break.
+    pub(crate) fn print(
+        &self,
+        out: &mut dyn Write,
+        max_line_length: isize,
+        ann: &dyn PpAnn,
+    ) -> io::Result<()> {
+        let sizes = self.scan();
+        let mut space = max_line_length;
+        let mut indent: isize = 0;
+        let mut stack: Vec<Frame> = Vec::new();
+
+        let fits = |size: isize, space: isize| size <= space;
+
+        for (i, token) in self.tokens.iter().enumerate() {
+            match token {
+                Token::Begin { offset, breaks } => {
+                    let mode = if fits(sizes[i], space) {
+                        Mode::Fits
+                    } else {
+                        Mode::Break
+                    };
+                    stack.push(Frame {
+                        mode,
+                        breaks: *breaks,
+                        indent,
+                    });
+                    indent += offset;
+                }
+                Token::End => {
+                    if let Some(frame) = stack.pop() {
+                        indent = frame.indent;
+                    }
+                }
+                Token::Break { blank, offset } => {
+                    let frame = stack.last();
+                    let breaking = match frame {
+                        Some(Frame {
+                            mode: Mode::Break,
+                            breaks: Breaks::Consistent,
+                            ..
+                        }) => true,
+                        Some(Frame {
+                            mode: Mode::Break,
+                            breaks: Breaks::Inconsistent,
+                            ..
+                        }) => !fits(sizes[i], space),
+                        _ => false,
+                    };
+
+                    if breaking {
+                        let column = indent + offset;
+                        writeln!(out)?;
+                        write!(out, "{:>width$}", "", width = column.max(0) as usize)?;
+                        space = max_line_length - column;
+                    } else {
+                        write!(out, "{:>width$}", "", width = *blank)?;
+                        space -= *blank as isize;
+                    }
+                }
+                Token::String(s) => {
+                    write!(out, "{}", s)?;
+                    space -= Self::width(token);
+                }
+                Token::Ann { phase, node } => match phase {
+                    AnnPhase::Pre => ann.pre(*node, out)?,
+                    AnnPhase::Post => ann.post(*node, out),
+                },
+                Token::Newline => {
+                    writeln!(out)?;
+                    space = max_line_length;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}